@@ -0,0 +1,35 @@
+use crate::*;
+
+//Default cap applied until the owner calls `set_config`; effectively
+//unlimited so existing deployments aren't retroactively constrained
+pub const DEFAULT_MAX_ACCOUNTS_PER_USER: u32 = u32::MAX;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Config {
+    pub max_accounts_per_user: u32,
+    pub general_settings: UnorderedMap<String, String>
+}
+
+impl Config {
+    pub fn new() -> Self {
+        Self {
+            max_accounts_per_user: DEFAULT_MAX_ACCOUNTS_PER_USER,
+            general_settings: UnorderedMap::new(b"gst".to_vec())
+        }
+    }
+}
+
+//Serializable snapshot of `Config` returned by `get_config`; `UnorderedMap`
+//itself doesn't implement `Serialize`
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ConfigView {
+    pub max_accounts_per_user: u32,
+    pub general_settings: Vec<(String, String)>
+}
+
+impl PassManager {
+    pub(crate) fn assert_owner(&self) {
+        assert_eq!(env::predecessor_account_id(), self.owner_id, "Only the contract owner may perform this action");
+    }
+}
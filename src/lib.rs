@@ -1,14 +1,14 @@
-use base64::{encode, decode};
-
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{UnorderedSet, UnorderedMap};
+use near_sdk::collections::{UnorderedSet, UnorderedMap, Vector};
 use near_sdk::{env, near_bindgen, AccountId, PanicOnDefault, Balance, Promise};
 use near_sdk::serde::{Serialize, Deserialize};
-use near_sdk::json_types::U128;
+use near_sdk::json_types::{U128, U64};
 
 use crate::user_account::*;
+use crate::config::*;
 
 mod user_account;
+mod config;
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
@@ -16,43 +16,60 @@ pub struct PassManager {
     pub owner_id: AccountId,
     pub accounts_per_user: UnorderedMap<AccountId, UnorderedSet<UserAccountId>>,
     pub accounts_by_id: UnorderedMap<UserAccountId, UserAccount>,
-    pub account_id_counter: UserAccountId
+    pub account_id_counter: UserAccountId,
+    pub shared_access: UnorderedMap<UserAccountId, UnorderedMap<AccountId, u64>>,
+    pub history: UnorderedMap<UserAccountId, Vector<UserAccount>>,
+    pub config: Config
 }
 
 #[near_bindgen]
 impl PassManager{
     #[init]
     pub fn new(owner_id: AccountId) -> Self{
-        Self { 
-            owner_id, 
+        Self {
+            owner_id,
             accounts_per_user: UnorderedMap::new("apu".try_to_vec().unwrap()),
             accounts_by_id: UnorderedMap::new("abi".try_to_vec().unwrap()),
-            account_id_counter: 0
+            account_id_counter: 0,
+            shared_access: UnorderedMap::new("sha".try_to_vec().unwrap()),
+            history: UnorderedMap::new("his".try_to_vec().unwrap()),
+            config: Config::new()
         }
     }
 
     #[payable]
-    pub fn add_account(&mut self, user_id: AccountId, website: String, mut username: String, mut password: String){
+    pub fn add_account(&mut self, user_id: AccountId, website: String, username: EncryptedCredential, password: EncryptedCredential){
+        self.require_owner_or_self(&user_id);
 
         //Assert deposit is attached = full access key provided
         assert!(env::attached_deposit() >= 1, "Required attached deposit of at least 1 yoctoNEAR");
+
+        //Reject malformed crypto sections before they ever reach storage
+        validate_encrypted_credential(&username);
+        validate_encrypted_credential(&password);
+
         let init_storage_used = env::storage_usage();
 
         let mut id: u128 = 0;
-
-        //Check if account is new or needs to be updated
-        if let Some(account) = self.get_one_account(user_id.clone(), website.clone()){
-            id = account.id;
+        let mut version: u64 = 0;
+
+        //Check if account is new or needs to be updated, pushing the
+        //superseded version into history when it does
+        if let Some(existing) = self.get_one_account(user_id.clone(), website.clone()){
+            id = existing.id;
+            version = existing.version + 1;
+            self.push_history(&id, &existing);
         }else{
+            let accounts_count = self.accounts_per_user.get(&user_id).map(|s| s.len()).unwrap_or(0);
+            if accounts_count >= self.config.max_accounts_per_user as u64 {
+                env::panic_str(&format!("Max accounts per user limit of {} reached", self.config.max_accounts_per_user));
+            }
+
             self.account_id_counter += 1;
-            id = self.account_id_counter; 
+            id = self.account_id_counter;
         }
 
-        //Base64 encode
-        username = encode(username);
-        password = encode(password);
-
-        let account = UserAccount { id, user_id: user_id.clone(), website, username, password };
+        let account = UserAccount { id, user_id: user_id.clone(), website, username, password, version };
 
         self.accounts_by_id.insert(&account.id, &account);
         self.add_account_to_user(&user_id, &account.id); 
@@ -77,26 +94,35 @@ impl PassManager{
         
     }
 
+    //Returns the stored blobs as-is; decryption happens entirely client-side.
+    //Gated like the mutating methods: only the account owner or the contract
+    //owner may call this, so it must be invoked as a function-call
+    //transaction rather than an RPC view query
     pub fn get_one_account(&self, user_id: AccountId, website: String) -> Option<UserAccount>{
+        self.require_owner_or_self(&user_id);
+
         if let Some(acc_set) = self.accounts_per_user.get(&user_id){
             for acc in acc_set.iter(){
-                let mut a = self.accounts_by_id.get(&acc).unwrap();
+                let a = self.accounts_by_id.get(&acc).unwrap();
                 if &a.website == &website{
-                    decode_credentials(&mut a);
                     return Some(a);
                 }
             }
-        }        
+        }
         None
     }
 
+    //Returns the stored blobs as-is; decryption happens entirely client-side.
+    //Gated like the mutating methods: only the account owner or the contract
+    //owner may call this, so it must be invoked as a function-call
+    //transaction rather than an RPC view query
     pub fn get_accounts_per_user(&self, user_id: AccountId) -> Vec<UserAccount>{
+        self.require_owner_or_self(&user_id);
+
         let acc_set = self.accounts_per_user.get(&user_id).expect("Invalid user");
 
         acc_set.iter().map(|x| {
-            let mut acc = self.accounts_by_id.get(&x).unwrap();
-            decode_credentials(&mut acc);
-            acc            
+            self.accounts_by_id.get(&x).unwrap()
         }).collect()
 
     }
@@ -104,14 +130,33 @@ impl PassManager{
     //Get all accounts without decrypting for testing
     /*
     pub fn get_all_accounts(&self) -> Vec<UserAccount>{
-        self.accounts_by_id.iter().map(|(_k, v)| v).collect()        
+        self.accounts_by_id.iter().map(|(_k, v)| v).collect()
     }*/
 
+    //Paginated alternative to `get_accounts_per_user` so gas doesn't scale
+    //with the full set size; still returns opaque (encrypted) blobs.
+    //No signer-based auth: this is a view method with no predecessor in an
+    //RPC view query, unlike `get_accounts_per_user`, which is gated
+    pub fn get_accounts_paged(&self, user_id: AccountId, from_index: U64, limit: u64) -> Vec<UserAccount>{
+        let acc_set = self.accounts_per_user.get(&user_id).expect("Invalid user");
+
+        acc_set.iter().skip(from_index.0 as usize).take(limit as usize).map(|x| {
+            self.accounts_by_id.get(&x).unwrap()
+        }).collect()
+    }
+
+    pub fn get_accounts_count(&self, user_id: AccountId) -> U128{
+        let count = self.accounts_per_user.get(&user_id).map(|s| s.len()).unwrap_or(0);
+        U128(count as u128)
+    }
+
     pub fn get_users_count(&self) -> U128{
         U128(self.accounts_per_user.len() as u128)
     }
 
     pub fn remove_account(&mut self, user_id: AccountId, account_id: UserAccountId){
+        self.require_owner_or_self(&user_id);
+
         let init_storage_used = env::storage_usage();
         
         let removed_from_user = self.remove_account_from_user(&user_id, &account_id);
@@ -129,6 +174,185 @@ impl PassManager{
             Promise::new(env::predecessor_account_id()).transfer(refund);
         }
     }
+
+    //Grants another account read access to one of the caller's credentials.
+    //`duration_ns` is relative to the current block timestamp; None grants
+    //permanent access. Charges for the storage the new/updated grant uses,
+    //same as add_account.
+    #[payable]
+    pub fn grant_access(&mut self, account_id: U128, grantee: AccountId, duration_ns: Option<U64>){
+        let account_id: UserAccountId = account_id.0;
+        let account = self.accounts_by_id.get(&account_id).expect("Account not found");
+        assert_eq!(account.user_id, env::predecessor_account_id(), "Only the account owner can grant access");
+
+        assert!(env::attached_deposit() >= 1, "Required attached deposit of at least 1 yoctoNEAR");
+
+        let expiry = match duration_ns {
+            Some(duration) => env::block_timestamp().saturating_add(duration.0),
+            None => u64::MAX
+        };
+
+        let init_storage_used = env::storage_usage();
+
+        let mut grantees = self.shared_access.get(&account_id).unwrap_or_else(||{
+            UnorderedMap::new(hash_user_account_id(b"sha", &account_id).try_to_vec().unwrap())
+        });
+        grantees.insert(&grantee, &expiry);
+        self.shared_access.insert(&account_id, &grantees);
+
+        let storage_used = env::storage_usage() - init_storage_used;
+        let required_cost = env::storage_byte_cost() * Balance::from(storage_used);
+        let attached_deposit = env::attached_deposit();
+
+        //Undo the grant and panic if attached deposit < required cost
+        if required_cost > attached_deposit {
+            grantees.remove(&grantee);
+            if grantees.is_empty(){
+                self.shared_access.remove(&account_id);
+            }else{
+                self.shared_access.insert(&account_id, &grantees);
+            }
+            env::panic_str(&format!("Must attach {} yoctoNEAR to cover storage", required_cost));
+        }
+
+        let refund = attached_deposit - required_cost;
+
+        //Refund remaining yocto if greater than 1
+        if refund > 1 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+    }
+
+    //Releases the storage held by a grant and refunds it, same as
+    //remove_account.
+    pub fn revoke_access(&mut self, account_id: U128, grantee: AccountId){
+        let account_id: UserAccountId = account_id.0;
+        let account = self.accounts_by_id.get(&account_id).expect("Account not found");
+        assert_eq!(account.user_id, env::predecessor_account_id(), "Only the account owner can revoke access");
+
+        let init_storage_used = env::storage_usage();
+
+        let mut grantees = self.shared_access.get(&account_id).expect("No shared access for this account");
+        grantees.remove(&grantee);
+
+        if grantees.is_empty(){
+            self.shared_access.remove(&account_id);
+        }else{
+            self.shared_access.insert(&account_id, &grantees);
+        }
+
+        let storage_released = init_storage_used - env::storage_usage();
+        let refund = env::storage_byte_cost() * Balance::from(storage_released);
+
+        //Refund for releasing storage if greater than 1 yocto
+        if refund > 1 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+    }
+
+    //Returns the credential iff the caller is the owner or a grantee whose
+    //access has not expired. Expired grants are treated as absent.
+    pub fn get_shared_account(&self, account_id: U128) -> Option<UserAccount>{
+        let account_id: UserAccountId = account_id.0;
+        let account = self.accounts_by_id.get(&account_id)?;
+        let caller = env::predecessor_account_id();
+
+        if account.user_id == caller {
+            return Some(account);
+        }
+
+        if let Some(grantees) = self.shared_access.get(&account_id) {
+            if let Some(expiry) = grantees.get(&caller) {
+                if expiry > env::block_timestamp() {
+                    return Some(account);
+                }
+            }
+        }
+        None
+    }
+
+    //Returns a page of an account's prior versions, oldest-superseded-first
+    //(insertion order), i.e. version 0 comes first. Gated like
+    //get_one_account: only the account owner or the contract owner may
+    //call this, so it must be invoked as a function-call transaction
+    //rather than an RPC view query
+    pub fn get_account_history(&self, account_id: U128, from_index: U64, limit: U64) -> Vec<UserAccount>{
+        let account_id: UserAccountId = account_id.0;
+        let account = self.accounts_by_id.get(&account_id).expect("Account not found");
+        self.require_owner_or_self(&account.user_id);
+
+        match self.history.get(&account_id) {
+            Some(hist) => hist.iter().skip(from_index.0 as usize).take(limit.0 as usize).collect(),
+            None => vec![]
+        }
+    }
+
+    //Restores a prior encrypted blob as a new current version, pushing
+    //today's current version into history first
+    #[payable]
+    pub fn rollback_account(&mut self, account_id: U128, target_version: u64) -> UserAccount{
+        let account_id: UserAccountId = account_id.0;
+        let current = self.accounts_by_id.get(&account_id).expect("Account not found");
+        self.require_owner_or_self(&current.user_id);
+
+        assert!(env::attached_deposit() >= 1, "Required attached deposit of at least 1 yoctoNEAR");
+
+        let hist = self.history.get(&account_id).expect("No history for this account");
+        let target = hist.iter().find(|a| a.version == target_version).expect("Target version not found");
+
+        let init_storage_used = env::storage_usage();
+
+        self.push_history(&account_id, &current);
+
+        let restored = UserAccount {
+            id: account_id,
+            user_id: current.user_id.clone(),
+            website: target.website.clone(),
+            username: target.username.clone(),
+            password: target.password.clone(),
+            version: current.version + 1
+        };
+        self.accounts_by_id.insert(&account_id, &restored);
+
+        let storage_used = env::storage_usage() - init_storage_used;
+        let required_cost = env::storage_byte_cost() * Balance::from(storage_used);
+        let attached_deposit = env::attached_deposit();
+
+        //Undo the rollback and panic if attached deposit < required cost
+        if required_cost > attached_deposit {
+            self.accounts_by_id.insert(&account_id, &current);
+            let mut hist = self.history.get(&account_id).unwrap();
+            hist.pop();
+            self.history.insert(&account_id, &hist);
+            env::panic_str(&format!("Must attach {} yoctoNEAR to cover storage", required_cost));
+        }
+
+        let refund = attached_deposit - required_cost;
+
+        //Refund remaining yocto if greater than 1
+        if refund > 1 {
+            Promise::new(env::predecessor_account_id()).transfer(refund);
+        }
+
+        restored
+    }
+
+    pub fn set_config(&mut self, max_accounts_per_user: u32){
+        self.assert_owner();
+        self.config.max_accounts_per_user = max_accounts_per_user;
+    }
+
+    pub fn update_setting(&mut self, key: String, value: String){
+        self.assert_owner();
+        self.config.general_settings.insert(&key, &value);
+    }
+
+    pub fn get_config(&self) -> ConfigView{
+        ConfigView {
+            max_accounts_per_user: self.config.max_accounts_per_user,
+            general_settings: self.config.general_settings.iter().collect()
+        }
+    }
 }
 
 
@@ -142,23 +366,65 @@ mod tests {
         if needs_deposit {
             VMContextBuilder::new()
             .signer_account_id("milos21.testnet".parse().unwrap())
+            .predecessor_account_id("milos21.testnet".parse().unwrap())
             .attached_deposit(10000000000000000000000)
             .is_view(false)
             .build()
         }else{
             VMContextBuilder::new()
             .signer_account_id("milos21.testnet".parse().unwrap())
+            .predecessor_account_id("milos21.testnet".parse().unwrap())
             .is_view(false)
             .build()
         }
     }
 
+    fn get_context_as(signer: &str, block_timestamp: u64) -> VMContext {
+        VMContextBuilder::new()
+        .signer_account_id(signer.parse().unwrap())
+        .predecessor_account_id(signer.parse().unwrap())
+        .block_timestamp(block_timestamp)
+        .is_view(false)
+        .build()
+    }
+
+    fn get_context_as_with_deposit(signer: &str) -> VMContext {
+        VMContextBuilder::new()
+        .signer_account_id(signer.parse().unwrap())
+        .predecessor_account_id(signer.parse().unwrap())
+        .attached_deposit(10000000000000000000000)
+        .is_view(false)
+        .build()
+    }
+
+    fn get_context_as_with_deposit_and_time(signer: &str, block_timestamp: u64) -> VMContext {
+        VMContextBuilder::new()
+        .signer_account_id(signer.parse().unwrap())
+        .predecessor_account_id(signer.parse().unwrap())
+        .attached_deposit(10000000000000000000000)
+        .block_timestamp(block_timestamp)
+        .is_view(false)
+        .build()
+    }
+
+    //Builds a well-formed (but not cryptographically meaningful) keystore
+    //blob for a given tag, so each test can have distinct fixtures
+    fn sample_credential(tag: &str) -> EncryptedCredential {
+        EncryptedCredential {
+            cipher: "aes-128-ctr".to_string(),
+            cipherparams: CipherParams { iv: format!("iv-{}", tag) },
+            ciphertext: format!("ciphertext-{}", tag),
+            kdf: Kdf::Scrypt { dklen: 32, salt: format!("salt-{}", tag), n: 8192, r: 8, p: 1 },
+            mac: format!("mac-{}", tag),
+        }
+    }
+
     #[test]
     fn add_account_success_test() {
         let context = get_context(true);
         testing_env!(context);
         let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
-        assert_eq!((), pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), "user1".to_string(), "pass1".to_string()));
+        assert_eq!((), pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1")));
     }
 
     #[test]
@@ -168,7 +434,18 @@ mod tests {
         let context = get_context(false);
         testing_env!(context);
         let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
-        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), "user1".to_string(), "pass1".to_string());    
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Unsupported cipher, expected aes-128-ctr"#)]
+    fn add_account_malformed_crypto_section_test(){
+        let context = get_context(true);
+        testing_env!(context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        let mut bad_username = sample_credential("user1");
+        bad_username.cipher = "aes-256-cbc".to_string();
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), bad_username, sample_credential("pass1"));
     }
 
     #[test]
@@ -176,14 +453,15 @@ mod tests {
         let context = get_context(true);
         testing_env!(context);
         let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
-        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), "user1".to_string(), "pass1".to_string());   
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
         assert_eq!(
             Some(UserAccount{
-                id: 1, 
-                user_id: "1.milos21.testnet".parse().unwrap(), 
-                website: "instagram".to_string(), 
-                username: "user1".to_string(),
-                password: "pass1".to_string()}), 
+                id: 1,
+                user_id: "1.milos21.testnet".parse().unwrap(),
+                website: "instagram".to_string(),
+                username: sample_credential("user1"),
+                password: sample_credential("pass1"),
+                version: 0}),
             pass_manager.get_one_account("1.milos21.testnet".parse().unwrap(), "instagram".parse().unwrap()));
     }
 
@@ -192,11 +470,11 @@ mod tests {
         let context = get_context(true);
         testing_env!(context);
         let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
-        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), "user1".to_string(), "pass1".to_string());   
-        
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+
         //Non existent user so it should return None
         assert_eq!(
-            None, 
+            None,
             pass_manager.get_one_account("2.milos21.testnet".parse().unwrap(), "instagram".parse().unwrap()));
     }
 
@@ -205,11 +483,11 @@ mod tests {
         let context = get_context(true);
         testing_env!(context);
         let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
-        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), "user1".to_string(), "pass1".to_string());   
-        
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+
         //Non existent website so it should return None
         assert_eq!(
-            None, 
+            None,
             pass_manager.get_one_account("1.milos21.testnet".parse().unwrap(), "facebook".parse().unwrap()));
     }
 
@@ -218,41 +496,42 @@ mod tests {
         let context = get_context(true);
         testing_env!(context);
         let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
-        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), "user1".to_string(), "pass1".to_string());   
-        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "facebook".to_string(), "user2".to_string(), "pass2".to_string());   
-        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "reddit".to_string(), "user3".to_string(), "pass3".to_string());
-        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "twitter".to_string(), "user4".to_string(), "pass4".to_string());   
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "facebook".to_string(), sample_credential("user2"), sample_credential("pass2"));
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "reddit".to_string(), sample_credential("user3"), sample_credential("pass3"));
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "twitter".to_string(), sample_credential("user4"), sample_credential("pass4"));
         let mut account = UserAccount{
-                                        id: 1,  
-                                        user_id: "1.milos21.testnet".parse().unwrap(), 
-                                        website: "instagram".to_string(), 
-                                        username: "user1".to_string(),
-                                        password: "pass1".to_string()
+                                        id: 1,
+                                        user_id: "1.milos21.testnet".parse().unwrap(),
+                                        website: "instagram".to_string(),
+                                        username: sample_credential("user1"),
+                                        password: sample_credential("pass1"),
+                                        version: 0
                                     };
         let mut acc_vec: Vec<UserAccount> = vec![];
         acc_vec.push(account.clone());
 
         account.id = 2;
         account.website = "facebook".to_string();
-        account.username = "user2".to_string();
-        account.password = "pass2".to_string();
+        account.username = sample_credential("user2");
+        account.password = sample_credential("pass2");
         acc_vec.push(account.clone());
 
         account.id = 3;
         account.website = "reddit".to_string();
-        account.username = "user3".to_string();
-        account.password = "pass3".to_string();
+        account.username = sample_credential("user3");
+        account.password = sample_credential("pass3");
         acc_vec.push(account.clone());
 
         account.id = 4;
         account.website = "twitter".to_string();
-        account.username = "user4".to_string();
-        account.password = "pass4".to_string();
+        account.username = sample_credential("user4");
+        account.password = sample_credential("pass4");
         acc_vec.push(account);
 
-        
+
         assert_eq!(
-            acc_vec, 
+            acc_vec,
             pass_manager.get_accounts_per_user("1.milos21.testnet".parse().unwrap()));
     }
 
@@ -262,8 +541,8 @@ mod tests {
         let context = get_context(true);
         testing_env!(context);
         let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
-        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), "user1".to_string(), "pass1".to_string());   
-                
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+
         //Non existent user, test should panic
         pass_manager.get_accounts_per_user("2.milos21.testnet".parse().unwrap());
     }
@@ -273,8 +552,8 @@ mod tests {
         let context = get_context(true);
         testing_env!(context);
         let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
-        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), "user1".to_string(), "pass1".to_string());   
-                
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+
         assert_eq!(U128(1), pass_manager.get_users_count());
     }
 
@@ -283,8 +562,8 @@ mod tests {
         let context = get_context(true);
         testing_env!(context);
         let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
-        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), "user1".to_string(), "pass1".to_string());   
-                
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+
         assert_eq!((), pass_manager.remove_account("1.milos21.testnet".parse().unwrap(), 1));
     }
 
@@ -294,8 +573,8 @@ mod tests {
         let context = get_context(true);
         testing_env!(context);
         let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
-        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), "user1".to_string(), "pass1".to_string());   
-                
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+
         //Non existent user, test should panic
         pass_manager.remove_account("2.milos21.testnet".parse().unwrap(), 1);
     }
@@ -306,9 +585,271 @@ mod tests {
         let context = get_context(true);
         testing_env!(context);
         let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
-        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), "user1".to_string(), "pass1".to_string());   
-                
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+
         //Non existent account, test should panic
         pass_manager.remove_account("1.milos21.testnet".parse().unwrap(), 2);
     }
+
+    #[test]
+    fn get_shared_account_owner_always_has_access_test(){
+        let context = get_context_as_with_deposit_and_time("1.milos21.testnet", 100);
+        testing_env!(context.clone());
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+
+        testing_env!(context);
+        assert!(pass_manager.get_shared_account(U128(1)).is_some());
+    }
+
+    #[test]
+    fn grant_access_permanent_test(){
+        let owner_context = get_context_as_with_deposit_and_time("1.milos21.testnet", 100);
+        testing_env!(owner_context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+        pass_manager.grant_access(U128(1), "friend.testnet".parse().unwrap(), None);
+
+        testing_env!(get_context_as("friend.testnet", u64::MAX - 1));
+        assert!(pass_manager.get_shared_account(U128(1)).is_some());
+    }
+
+    #[test]
+    fn grant_access_timed_expires_test(){
+        let owner_context = get_context_as_with_deposit_and_time("1.milos21.testnet", 100);
+        testing_env!(owner_context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+        pass_manager.grant_access(U128(1), "friend.testnet".parse().unwrap(), Some(U64(1000)));
+
+        testing_env!(get_context_as("friend.testnet", 500));
+        assert!(pass_manager.get_shared_account(U128(1)).is_some());
+
+        testing_env!(get_context_as("friend.testnet", 1100));
+        assert_eq!(None, pass_manager.get_shared_account(U128(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Only the account owner can grant access"#)]
+    fn grant_access_non_owner_test(){
+        let owner_context = get_context_as_with_deposit_and_time("1.milos21.testnet", 100);
+        testing_env!(owner_context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+
+        testing_env!(get_context_as("intruder.testnet", 100));
+        pass_manager.grant_access(U128(1), "friend.testnet".parse().unwrap(), None);
+    }
+
+    #[test]
+    fn revoke_access_test(){
+        let owner_context = get_context_as_with_deposit_and_time("1.milos21.testnet", 100);
+        testing_env!(owner_context.clone());
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+        pass_manager.grant_access(U128(1), "friend.testnet".parse().unwrap(), None);
+
+        testing_env!(owner_context);
+        pass_manager.revoke_access(U128(1), "friend.testnet".parse().unwrap());
+
+        testing_env!(get_context_as("friend.testnet", 200));
+        assert_eq!(None, pass_manager.get_shared_account(U128(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Only the account owner or the contract owner may perform this action"#)]
+    fn add_account_cross_user_test(){
+        let context = get_context_as("intruder.testnet", 100);
+        testing_env!(context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Only the account owner or the contract owner may perform this action"#)]
+    fn get_one_account_cross_user_test(){
+        let owner_context = get_context_as_with_deposit_and_time("1.milos21.testnet", 100);
+        testing_env!(owner_context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+
+        testing_env!(get_context_as("intruder.testnet", 100));
+        pass_manager.get_one_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Only the account owner or the contract owner may perform this action"#)]
+    fn get_accounts_per_user_cross_user_test(){
+        let owner_context = get_context_as_with_deposit_and_time("1.milos21.testnet", 100);
+        testing_env!(owner_context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+
+        testing_env!(get_context_as("intruder.testnet", 100));
+        pass_manager.get_accounts_per_user("1.milos21.testnet".parse().unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Only the account owner or the contract owner may perform this action"#)]
+    fn remove_account_cross_user_test(){
+        let owner_context = get_context_as_with_deposit_and_time("1.milos21.testnet", 100);
+        testing_env!(owner_context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+
+        testing_env!(get_context_as("intruder.testnet", 100));
+        pass_manager.remove_account("1.milos21.testnet".parse().unwrap(), 1);
+    }
+
+    #[test]
+    fn add_account_owner_admin_path_test(){
+        testing_env!(get_context_as_with_deposit("1.milos21.testnet"));
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+
+        //The contract owner is not the account holder, but may still act on
+        //its behalf for migration/recovery
+        testing_env!(get_context_as_with_deposit("milos21.testnet"));
+        assert_eq!((), pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1")));
+    }
+
+    #[test]
+    fn get_account_history_test(){
+        let context = get_context(true);
+        testing_env!(context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1-v2"), sample_credential("pass1-v2"));
+
+        let history = pass_manager.get_account_history(U128(1), U64(0), U64(10));
+        assert_eq!(1, history.len());
+        assert_eq!(0, history[0].version);
+        assert_eq!(sample_credential("user1"), history[0].username);
+
+        let current = pass_manager.get_one_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string()).unwrap();
+        assert_eq!(1, current.version);
+    }
+
+    #[test]
+    fn rollback_account_test(){
+        let context = get_context(true);
+        testing_env!(context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1-v2"), sample_credential("pass1-v2"));
+
+        let restored = pass_manager.rollback_account(U128(1), 0);
+        assert_eq!(sample_credential("user1"), restored.username);
+        assert_eq!(2, restored.version);
+
+        let current = pass_manager.get_one_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string()).unwrap();
+        assert_eq!(sample_credential("user1"), current.username);
+
+        //Both superseded versions are now in history
+        assert_eq!(2, pass_manager.get_account_history(U128(1), U64(0), U64(10)).len());
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Target version not found"#)]
+    fn rollback_account_invalid_version_test(){
+        let context = get_context(true);
+        testing_env!(context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1-v2"), sample_credential("pass1-v2"));
+
+        pass_manager.rollback_account(U128(1), 7);
+    }
+
+    #[test]
+    fn set_config_test(){
+        let context = get_context(true);
+        testing_env!(context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.set_config(2);
+
+        assert_eq!(2, pass_manager.get_config().max_accounts_per_user);
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Max accounts per user limit of 2 reached"#)]
+    fn add_account_over_limit_test(){
+        let context = get_context(true);
+        testing_env!(context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.set_config(2);
+
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "facebook".to_string(), sample_credential("user2"), sample_credential("pass2"));
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "reddit".to_string(), sample_credential("user3"), sample_credential("pass3"));
+    }
+
+    #[test]
+    fn add_account_update_existing_exempt_from_limit_test(){
+        let context = get_context(true);
+        testing_env!(context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.set_config(1);
+
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+
+        //Updating the same website is exempt from the limit
+        assert_eq!((), pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1-v2"), sample_credential("pass1-v2")));
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Only the contract owner may perform this action"#)]
+    fn set_config_non_owner_test(){
+        let context = get_context_as("intruder.testnet", 100);
+        testing_env!(context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.set_config(5);
+    }
+
+    #[test]
+    fn update_setting_test(){
+        let context = get_context(true);
+        testing_env!(context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.update_setting("theme".to_string(), "dark".to_string());
+
+        let config = pass_manager.get_config();
+        assert_eq!(vec![("theme".to_string(), "dark".to_string())], config.general_settings);
+    }
+
+    #[test]
+    fn get_accounts_paged_test(){
+        let context = get_context(true);
+        testing_env!(context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "facebook".to_string(), sample_credential("user2"), sample_credential("pass2"));
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "reddit".to_string(), sample_credential("user3"), sample_credential("pass3"));
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "twitter".to_string(), sample_credential("user4"), sample_credential("pass4"));
+
+        let page = pass_manager.get_accounts_paged("1.milos21.testnet".parse().unwrap(), U64(1), 2);
+        assert_eq!(2, page.len());
+        assert_eq!(vec![2, 3], page.iter().map(|a| a.id).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[should_panic(expected = r#"Invalid user"#)]
+    fn get_accounts_paged_non_existent_user_test(){
+        let context = get_context(true);
+        testing_env!(context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+
+        pass_manager.get_accounts_paged("2.milos21.testnet".parse().unwrap(), U64(0), 10);
+    }
+
+    #[test]
+    fn get_accounts_count_test(){
+        let context = get_context(true);
+        testing_env!(context);
+        let mut pass_manager = PassManager::new("milos21.testnet".parse().unwrap());
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "instagram".to_string(), sample_credential("user1"), sample_credential("pass1"));
+        pass_manager.add_account("1.milos21.testnet".parse().unwrap(), "facebook".to_string(), sample_credential("user2"), sample_credential("pass2"));
+
+        assert_eq!(U128(2), pass_manager.get_accounts_count("1.milos21.testnet".parse().unwrap()));
+    }
 }
@@ -3,15 +3,46 @@ use near_sdk::{CryptoHash};
 
 pub type UserAccountId = u128;
 
+/// `cipherparams` section of an `EncryptedCredential`, matching the Ethereum
+/// keystore `KeyFile` layout.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CipherParams {
+    pub iv: String,
+}
 
-#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+/// Key-derivation function and its parameters, as used by `ethstore`.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "kdf", content = "kdfparams", rename_all = "lowercase")]
+pub enum Kdf {
+    Scrypt { dklen: u32, salt: String, n: u32, r: u32, p: u32 },
+    Pbkdf2 { dklen: u32, salt: String, c: u32, prf: String },
+}
+
+/// A Web3-Secret-Storage-style encrypted blob. Produced and decrypted
+/// entirely client-side from the user's master passphrase; the contract
+/// only ever sees/stores ciphertext.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EncryptedCredential {
+    pub cipher: String,
+    pub cipherparams: CipherParams,
+    pub ciphertext: String,
+    #[serde(flatten)]
+    pub kdf: Kdf,
+    pub mac: String,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(crate = "near_sdk::serde")]
 pub struct UserAccount{
     pub id: UserAccountId,
     pub user_id: AccountId,
     pub website: String,
-    pub username: String,
-    pub password: String
+    pub username: EncryptedCredential,
+    pub password: EncryptedCredential,
+    pub version: u64
 
 }
 
@@ -21,29 +52,58 @@ pub(crate) fn hash_account_id(account_id: &AccountId) -> CryptoHash{
     hash
 }
 
-pub(crate) fn decode_credentials(account: &mut UserAccount){
-    let decoded = match decode(&account.username){
-        Ok(d) => d,
-        Err(_) => env::panic_str("Cannot decode username")
-    };
-    let username = match std::str::from_utf8(&decoded){
-        Ok(u) => u,
-        Err(_) => env::panic_str("Not a string")
-    };
-    account.username = username.to_string();
-
-    let decoded = match decode(&account.password){
-        Ok(d) => d,
-        Err(_) => env::panic_str("Cannot decode password")
-    };
-    let pass = match std::str::from_utf8(&decoded){
-        Ok(p) => p,
-        Err(_) => env::panic_str("Not a string")
-    };
-    account.password = pass.to_string();
+//`namespace` separates storage prefixes of the various per-account
+//collections (shared_access, history, ...) that are all keyed by the same
+//`UserAccountId` so they never collide on-disk.
+pub(crate) fn hash_user_account_id(namespace: &[u8], account_id: &UserAccountId) -> CryptoHash{
+    let mut bytes = namespace.to_vec();
+    bytes.extend_from_slice(&account_id.to_le_bytes());
+    let mut hash = CryptoHash::default();
+    hash.copy_from_slice(&env::sha256(&bytes));
+    hash
+}
+
+/// Rejects crypto sections that are structurally malformed before they are
+/// ever written to chain storage. This is not a cryptographic check (the
+/// contract cannot verify the MAC without the derived key) - it only
+/// guards against obviously broken blobs.
+pub(crate) fn validate_encrypted_credential(credential: &EncryptedCredential){
+    if credential.cipher != "aes-128-ctr" {
+        env::panic_str("Unsupported cipher, expected aes-128-ctr");
+    }
+    if credential.cipherparams.iv.is_empty() {
+        env::panic_str("Missing cipherparams.iv");
+    }
+    if credential.ciphertext.is_empty() {
+        env::panic_str("Missing ciphertext");
+    }
+    if credential.mac.is_empty() {
+        env::panic_str("Missing mac");
+    }
+    match &credential.kdf {
+        Kdf::Scrypt { dklen, salt, n, r, p } => {
+            if *dklen == 0 || salt.is_empty() || *n == 0 || *r == 0 || *p == 0 {
+                env::panic_str("Malformed scrypt kdfparams");
+            }
+        }
+        Kdf::Pbkdf2 { dklen, salt, c, prf } => {
+            if *dklen == 0 || salt.is_empty() || *c == 0 || prf.is_empty() {
+                env::panic_str("Malformed pbkdf2 kdfparams");
+            }
+        }
+    }
 }
 
 impl PassManager{
+    //Only the user themselves, or the contract owner acting on an
+    //admin/migration path, may act on a given user's accounts
+    pub(crate) fn require_owner_or_self(&self, user_id: &AccountId){
+        let caller = env::predecessor_account_id();
+        if &caller != user_id && caller != self.owner_id {
+            env::panic_str("Only the account owner or the contract owner may perform this action");
+        }
+    }
+
     pub(crate)fn add_account_to_user(&mut self, user_id: &AccountId, account_id: &UserAccountId){
         let mut account_set = self.accounts_per_user.get(user_id).unwrap_or_else(||{
             UnorderedSet::new(hash_account_id(user_id).try_to_vec().unwrap())
@@ -64,5 +124,15 @@ impl PassManager{
             self.accounts_per_user.insert(user_id, &account_set);
         }
         removed
-    }    
+    }
+
+    //Appends a superseded version of an account to its history, creating
+    //the per-account history Vector on first use
+    pub(crate) fn push_history(&mut self, account_id: &UserAccountId, account: &UserAccount){
+        let mut hist = self.history.get(account_id).unwrap_or_else(||{
+            Vector::new(hash_user_account_id(b"his", account_id).try_to_vec().unwrap())
+        });
+        hist.push(account);
+        self.history.insert(account_id, &hist);
+    }
 }
\ No newline at end of file